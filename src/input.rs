@@ -16,10 +16,30 @@ use std::u32;
 
 use syntax;
 
+use memchr::{memchr, memchr2, memchr3, memrchr, memrchr2, memrchr3};
+
 use utf8::{decode_utf8, decode_last_utf8};
 use literals::LiteralSearcher;
 
 /// Represents a location in the input.
+///
+/// This is the currency that `Input` implementations and the matching
+/// engines trade in. Constructing one directly (via `InputAt::new`) is how
+/// a caller plugs a custom `Input` implementation into the rest of the
+/// crate, so the relationship between the fields is part of this type's
+/// public contract:
+///
+/// * `pos` is the byte offset this position refers to.
+/// * `len` is the number of bytes the character/byte at `pos` occupies.
+///   `CharInput` reports 0 when `pos` is past the end of the input, or when
+///   the bytes at `pos` don't decode as UTF-8; `ByteInput` always reports 1,
+///   including past the end.
+/// * `c` and `byte` describe what occupies `[pos, pos + len)`: at most one
+///   of them is present (`c` for a `CharInput`-style reader that decodes
+///   Unicode scalar values, `byte` for a `ByteInput`-style reader that
+///   doesn't); both are absent when `pos` is past the end, and `CharInput`
+///   also leaves both absent at an in-bounds position holding invalid UTF-8.
+/// * `is_end()` being true is equivalent to both `c` and `byte` being absent.
 #[derive(Clone, Copy, Debug)]
 pub struct InputAt {
     pos: usize,
@@ -29,6 +49,19 @@ pub struct InputAt {
 }
 
 impl InputAt {
+    /// Builds a position directly from its constituent parts.
+    ///
+    /// This is the extension point for implementing `Input` over a custom
+    /// haystack (for example, a lazily-transcoded Latin-1 or UTF-16 reader):
+    /// such an implementation decodes whatever it's given into a `Char` (or
+    /// a raw `byte`) and hands back the `InputAt` describing it, without
+    /// needing access to anything private in this module. Callers must
+    /// uphold the field relationships documented on `InputAt` itself; the
+    /// matching engines rely on them and do no further validation.
+    pub fn new(pos: usize, len: usize, c: Char, byte: Option<u8>) -> InputAt {
+        InputAt { pos: pos, len: len, c: c, byte: byte }
+    }
+
     /// Returns true iff this position is at the beginning of the input.
     pub fn is_start(&self) -> bool {
         self.pos == 0
@@ -69,6 +102,24 @@ impl InputAt {
 }
 
 /// An abstraction over input used in the matching engines.
+///
+/// `CharInput` and `ByteInput` cover `&str` and `&[u8]` haystacks, but the
+/// trait is public and implementable so callers can plug in their own
+/// input source — for example an encoding-converting reader that
+/// transcodes Latin-1 or UTF-16 on the fly, or a `Cow`-backed view that
+/// only copies when it must. Implement `at`, `next_char`/`previous_char`
+/// and `prefix_at` in terms of `InputAt::new`, plus `len` and `as_bytes`,
+/// and the default `is_grapheme_boundary` plus the matching engines come
+/// along for free (the default `is_grapheme_boundary` reads `as_bytes` as
+/// UTF-8; see its docs).
+///
+/// NOTE: `Input`, `InputAt::new` and `Char` are `pub` here, but this module
+/// itself still needs to be re-exported (`pub use input::{Input, Char};`,
+/// plus an `InputAt` re-export) from the crate root, and the regex types
+/// need a generic `fn with<I: Input>(input: I)`-style entry point, before
+/// any of this is actually reachable by a downstream caller. Both of those
+/// live in `lib.rs` and the `Regex`/`RegexBuilder` types, which aren't part
+/// of this file and so aren't touched here.
 pub trait Input {
     /// Return an encoding of the position at byte offset `i`.
     fn at(&self, i: usize) -> InputAt;
@@ -83,6 +134,101 @@ pub trait Input {
     /// If no such character could be decoded, then `Char` is absent.
     fn previous_char(&self, at: InputAt) -> Char;
 
+    /// Returns true iff `at` falls on an extended grapheme cluster boundary,
+    /// per the UAX #29 rules approximated by `mod grapheme` below.
+    ///
+    /// The default implementation is built entirely out of `previous_char`,
+    /// `next_char` and `as_bytes`, so `CharInput` and `ByteInput` get this
+    /// for free. The GB11 and GB12/GB13 context scans decode `as_bytes()` as
+    /// UTF-8, stepping back from `at.pos()` by each character's UTF-8 width,
+    /// so they give correct results only when `as_bytes()` is UTF-8 text
+    /// indexed by the same byte offsets as `pos`.
+    ///
+    /// This is the Input-level primitive `\b{g}` needs, not `\b{g}` itself:
+    /// there is no parser token, no `Inst` variant, and no compiler or
+    /// engine support yet to actually expose it as an assertion, and none
+    /// of that lives in this file. Until that lands, call this method
+    /// directly; it is not wired into pattern matching.
+    ///
+    /// `mod grapheme`'s classification tables are also a hand-picked subset
+    /// of the `Extend`/`SpacingMark`/`Prepend` properties rather than the
+    /// full generated Unicode tables (this crate doesn't otherwise vendor
+    /// Unicode data), so results for codepoints outside the covered ranges
+    /// fall back to `Other` and may disagree with a conformant UAX #29
+    /// implementation.
+    fn is_grapheme_boundary(&self, at: InputAt) -> bool {
+        use self::grapheme::Class::*;
+
+        let before = match self.previous_char(at).as_char() {
+            None => return true, // GB1: sot ÷
+            Some(c) => c,
+        };
+        let after = match self.next_char(at).as_char() {
+            None => return true, // GB2: ÷ eot
+            Some(c) => c,
+        };
+        let (before_class, after_class) =
+            (grapheme::classify(before), grapheme::classify(after));
+
+        // GB3: CR × LF
+        if before_class == CR && after_class == LF {
+            return false;
+        }
+        // GB4: (Control|CR|LF) ÷
+        match before_class {
+            Control | CR | LF => return true,
+            _ => {}
+        }
+        // GB5: ÷ (Control|CR|LF)
+        match after_class {
+            Control | CR | LF => return true,
+            _ => {}
+        }
+        // GB6-GB8: don't break a Hangul syllable apart.
+        match (before_class, after_class) {
+            (L, L) | (L, V) | (L, LV) | (L, LVT) => return false,
+            (LV, V) | (LV, T) | (V, V) | (V, T) => return false,
+            (LVT, T) | (T, T) => return false,
+            _ => {}
+        }
+        // GB9: × (Extend|ZWJ)
+        match after_class {
+            Extend | Zwj => return false,
+            _ => {}
+        }
+        // GB9a: × SpacingMark
+        if after_class == SpacingMark {
+            return false;
+        }
+        // GB9b: Prepend ×
+        if before_class == Prepend {
+            return false;
+        }
+        // GB11: Extended_Pictographic Extend* ZWJ × Extended_Pictographic
+        if before_class == Zwj && after_class == ExtendedPictographic {
+            let zwj_start = at.pos() - before.len_utf8();
+            if grapheme::zwj_preceded_by_pictographic(
+                self.as_bytes(), zwj_start,
+            ) {
+                return false;
+            }
+        }
+        // GB12/GB13: keep paired Regional_Indicators together, but break
+        // between pairs.
+        if before_class == RegionalIndicator
+            && after_class == RegionalIndicator
+        {
+            let before_start = at.pos() - before.len_utf8();
+            if grapheme::odd_regional_indicator_run(
+                self.as_bytes(), before_start,
+            ) {
+                return false;
+            }
+        }
+        // GB999: Any ÷ Any
+        true
+    }
+
     /// Scan the input for a matching prefix.
     fn prefix_at(
         &self,
@@ -90,6 +236,81 @@ pub trait Input {
         at: InputAt,
     ) -> Option<InputAt>;
 
+    /// Scan the input for a matching prefix, falling back to `byteset`
+    /// when `prefixes` comes up empty.
+    ///
+    /// `byteset` is the set of bytes that can legally begin a match
+    /// (computed by the compiler from the pattern's first bytes). The
+    /// `LiteralSearcher` is tried first, since an exact literal is a
+    /// stronger filter than set membership; `byteset` is only consulted
+    /// when that search comes up empty, so patterns lacking a useful
+    /// literal prefix (a big alternation, a leading character class)
+    /// still get a prefilter instead of falling back to stepping through
+    /// the engines one character at a time.
+    ///
+    /// This is a separate method from `prefix_at` — rather than adding a
+    /// `byteset` parameter to it — so that existing callers of
+    /// `prefix_at` keep compiling unchanged; callers that have a
+    /// compile-time byteset available can opt into this one instead.
+    fn prefix_at_byteset(
+        &self,
+        prefixes: &LiteralSearcher,
+        byteset: Option<&ByteSet>,
+        at: InputAt,
+    ) -> Option<InputAt> {
+        match self.prefix_at(prefixes, at) {
+            Some(at) => Some(at),
+            None => byteset.and_then(|set| self.byteset_prefix_at(set, at)),
+        }
+    }
+
+    /// Scan the input starting at `at` for the first byte in `set`.
+    ///
+    /// This is a cheap prefilter for patterns whose possible starting
+    /// bytes form a set too large (or too irregular) to be worth compiling
+    /// into a `LiteralSearcher`, e.g. a character class or a big
+    /// alternation of single-byte literals. Like `prefix_at`, a `None`
+    /// result means no match is possible anywhere in the remaining input.
+    ///
+    /// The default implementation only needs `as_bytes` and `at`, so
+    /// `CharInput` and `ByteInput` share it. `prefix_at` is the usual entry
+    /// point; call this directly only when there's no `LiteralSearcher` to
+    /// consult at all.
+    fn byteset_prefix_at(
+        &self,
+        set: &ByteSet,
+        at: InputAt,
+    ) -> Option<InputAt> {
+        set.find(&self.as_bytes()[at.pos()..])
+            .map(|i| self.at(at.pos() + i))
+    }
+
+    /// Scan the input *backward* from `at` for the last byte in `set`.
+    ///
+    /// This is the reverse-direction counterpart to `byteset_prefix_at`:
+    /// it lets a reverse scan (used to pin down match starts, and for
+    /// right-anchored searches) skip past spans of the input that can't
+    /// possibly end a match, the same way `byteset_prefix_at` skips spans
+    /// that can't possibly start one. `byteset` is the set of bytes the
+    /// compiler determined can legally end a match; `None` means no such
+    /// set was computed for this pattern, in which case there is nothing
+    /// to filter on and this returns `None` unconditionally.
+    ///
+    /// Unlike `prefix_at`, this has no `LiteralSearcher`-backed
+    /// counterpart: `LiteralSearcher` has no reverse `rfind`, so an exact
+    /// required-suffix prefilter isn't available yet. This byteset-only
+    /// version is the reverse prefilter that's actually achievable with
+    /// what this crate has today.
+    fn suffix_at(
+        &self,
+        byteset: Option<&ByteSet>,
+        at: InputAt,
+    ) -> Option<InputAt> {
+        byteset
+            .and_then(|set| set.rfind(&self.as_bytes()[..at.pos()]))
+            .map(|i| self.at(i))
+    }
+
     /// The number of bytes in the input.
     fn len(&self) -> usize;
 
@@ -117,14 +338,135 @@ impl<'a, T: Input> Input for &'a T {
     fn as_bytes(&self) -> &[u8] { (**self).as_bytes() }
 }
 
+/// A 256-bit bitset of bytes, used as a cheap prefilter for the set of
+/// bytes that can legally begin (or end) a match.
+///
+/// For sets of size 1-3, `find`/`rfind` dispatch to
+/// `memchr`/`memchr2`/`memchr3` and their reverse counterparts. Larger
+/// sets fall back to a scalar membership scan, which is still far
+/// cheaper than stepping through the matching engines one character at a
+/// time.
+#[derive(Clone, Debug)]
+pub struct ByteSet {
+    bits: [u64; 4],
+    len: usize,
+}
+
+impl Default for ByteSet {
+    fn default() -> ByteSet { ByteSet::new() }
+}
+
+impl ByteSet {
+    /// Returns an empty byte set.
+    pub fn new() -> ByteSet {
+        ByteSet { bits: [0; 4], len: 0 }
+    }
+
+    /// Builds a byte set containing exactly the given bytes.
+    pub fn from_bytes(bytes: &[u8]) -> ByteSet {
+        let mut set = ByteSet::new();
+        for &b in bytes {
+            set.insert(b);
+        }
+        set
+    }
+
+    /// Adds `byte` to the set.
+    pub fn insert(&mut self, byte: u8) {
+        let word = (byte / 64) as usize;
+        let bit = 1u64 << (byte % 64);
+        if self.bits[word] & bit == 0 {
+            self.bits[word] |= bit;
+            self.len += 1;
+        }
+    }
+
+    /// Returns true iff `byte` is a member of this set.
+    #[inline]
+    pub fn contains(&self, byte: u8) -> bool {
+        let word = (byte / 64) as usize;
+        let bit = 1u64 << (byte % 64);
+        self.bits[word] & bit != 0
+    }
+
+    /// Returns the number of bytes in this set.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true iff this set has no members.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the set's members, in ascending order. Only ever called
+    /// when `len() <= 3`, to feed the small-set `memchr` dispatch below.
+    fn members(&self) -> [u8; 3] {
+        let mut members = [0u8; 3];
+        let mut i = 0;
+        for b in 0..256u32 {
+            if i >= members.len() {
+                break;
+            }
+            if self.contains(b as u8) {
+                members[i] = b as u8;
+                i += 1;
+            }
+        }
+        members
+    }
+
+    /// Returns the byte offset of the first member of this set occurring
+    /// in `haystack`, or `None` if no member occurs anywhere in it.
+    pub fn find(&self, haystack: &[u8]) -> Option<usize> {
+        match self.len {
+            0 => None,
+            1 => memchr(self.members()[0], haystack),
+            2 => {
+                let m = self.members();
+                memchr2(m[0], m[1], haystack)
+            }
+            3 => {
+                let m = self.members();
+                memchr3(m[0], m[1], m[2], haystack)
+            }
+            _ => haystack.iter().position(|&b| self.contains(b)),
+        }
+    }
+
+    /// Returns the byte offset of the last member of this set occurring
+    /// in `haystack`, or `None` if no member occurs anywhere in it.
+    pub fn rfind(&self, haystack: &[u8]) -> Option<usize> {
+        match self.len {
+            0 => None,
+            1 => memrchr(self.members()[0], haystack),
+            2 => {
+                let m = self.members();
+                memrchr2(m[0], m[1], haystack)
+            }
+            3 => {
+                let m = self.members();
+                memrchr3(m[0], m[1], m[2], haystack)
+            }
+            _ => haystack.iter().rposition(|&b| self.contains(b)),
+        }
+    }
+}
+
 /// An input reader over characters.
 #[derive(Clone, Copy, Debug)]
-pub struct CharInput<'t>(&'t [u8]);
+pub struct CharInput<'t> {
+    text: &'t [u8],
+    // Set once at construction by a single `is_ascii` scan of `text`. When
+    // true, every position in the input can be decoded with the ASCII fast
+    // path below, so the multi-byte decoder is never invoked.
+    only_ascii: bool,
+}
 
 impl<'t> CharInput<'t> {
     /// Return a new character input reader for the given string.
     pub fn new(s: &'t [u8]) -> CharInput<'t> {
-        CharInput(s)
+        CharInput { text: s, only_ascii: s.is_ascii() }
     }
 }
 
@@ -132,7 +474,7 @@ impl<'t> ops::Deref for CharInput<'t> {
     type Target = [u8];
 
     fn deref(&self) -> &[u8] {
-        self.0
+        self.text
     }
 }
 
@@ -144,12 +486,18 @@ impl<'t> Input for CharInput<'t> {
     // used *a lot* in the guts of the matching engines.
     #[inline(always)]
     fn at(&self, i: usize) -> InputAt {
-        let c = decode_utf8(&self[i..]).map(|(c, _)| c).into();
-        InputAt {
-            pos: i,
-            c: c,
-            byte: None,
-            len: c.len_utf8(),
+        // Most real-world text is ASCII, so avoid the multi-byte decoder
+        // whenever we already know (or can cheaply tell) that the byte at
+        // `i` can't be the start of a multi-byte sequence.
+        if self.only_ascii {
+            return self.at_ascii(i);
+        }
+        match self.text.get(i) {
+            Some(&b) if b < 0x80 => self.at_ascii(i),
+            _ => {
+                let c: Char = decode_utf8(&self[i..]).map(|(c, _)| c).into();
+                InputAt::new(i, c.len_utf8(), c, None)
+            }
         }
     }
 
@@ -158,6 +506,9 @@ impl<'t> Input for CharInput<'t> {
     }
 
     fn previous_char(&self, at: InputAt) -> Char {
+        if at.pos() > 0 && self.text[at.pos() - 1] < 0x80 {
+            return Char::from(self.text[at.pos() - 1] as char);
+        }
         decode_last_utf8(&self[..at.pos()]).map(|(c, _)| c).into()
     }
 
@@ -170,11 +521,27 @@ impl<'t> Input for CharInput<'t> {
     }
 
     fn len(&self) -> usize {
-        self.0.len()
+        self.text.len()
     }
 
     fn as_bytes(&self) -> &[u8] {
-        self.0
+        self.text
+    }
+}
+
+impl<'t> CharInput<'t> {
+    /// Decodes the position at byte offset `i`, under the assumption that
+    /// the byte at `i` (if any) is ASCII. This skips `decode_utf8` entirely,
+    /// since a single ASCII byte is already its own one-byte encoding.
+    #[inline(always)]
+    fn at_ascii(&self, i: usize) -> InputAt {
+        match self.text.get(i) {
+            None => InputAt::new(i, 0, None.into(), None),
+            Some(&b) => {
+                debug_assert!(b < 0x80);
+                InputAt::new(i, 1, (b as char).into(), None)
+            }
+        }
     }
 }
 
@@ -204,12 +571,7 @@ impl<'t> ops::Deref for ByteInput<'t> {
 impl<'t> Input for ByteInput<'t> {
     #[inline(always)]
     fn at(&self, i: usize) -> InputAt {
-        InputAt {
-            pos: i,
-            c: None.into(),
-            byte: self.get(i).map(|&b| b),
-            len: 1,
-        }
+        InputAt::new(i, 1, None.into(), self.get(i).map(|&b| b))
     }
 
     fn next_char(&self, at: InputAt) -> Char {
@@ -331,3 +693,414 @@ impl PartialOrd<Char> for char {
         (*self as u32).partial_cmp(&other.0)
     }
 }
+
+/// Classification of codepoints for UAX #29 extended grapheme cluster
+/// boundary rules, and the small amount of backward-scanning needed to
+/// resolve the context-sensitive rules (GB11, GB12/GB13).
+mod grapheme {
+    use utf8::decode_last_utf8;
+
+    /// The subset of `Grapheme_Cluster_Break` values referenced by the
+    /// UAX #29 rules. Anything not covered by one of these classes falls
+    /// back to `Other`, which only ever participates in GB999.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum Class {
+        Other,
+        CR,
+        LF,
+        Control,
+        Extend,
+        Zwj,
+        Prepend,
+        SpacingMark,
+        L,
+        V,
+        T,
+        LV,
+        LVT,
+        RegionalIndicator,
+        ExtendedPictographic,
+    }
+
+    /// Classifies a single codepoint.
+    ///
+    /// The Hangul classes (L/V/T/LV/LVT) are computed algorithmically,
+    /// since Hangul syllable composition is defined that way in Unicode.
+    /// The remaining classes are approximated with the most common
+    /// ranges for each property rather than the full generated tables
+    /// (this crate doesn't otherwise vendor Unicode character tables).
+    pub fn classify(c: char) -> Class {
+        match c as u32 {
+            0x000D => return Class::CR,
+            0x000A => return Class::LF,
+            0x200D => return Class::Zwj,
+            0x1F1E6..=0x1F1FF => return Class::RegionalIndicator,
+            _ => {}
+        }
+        if is_hangul_l(c) {
+            return Class::L;
+        }
+        if is_hangul_v(c) {
+            return Class::V;
+        }
+        if is_hangul_t(c) {
+            return Class::T;
+        }
+        if let Some(class) = hangul_syllable_class(c) {
+            return class;
+        }
+        if is_control(c) {
+            return Class::Control;
+        }
+        if is_prepend(c) {
+            return Class::Prepend;
+        }
+        if is_extend(c) {
+            return Class::Extend;
+        }
+        if is_spacing_mark(c) {
+            return Class::SpacingMark;
+        }
+        if is_extended_pictographic(c) {
+            return Class::ExtendedPictographic;
+        }
+        Class::Other
+    }
+
+    fn is_hangul_l(c: char) -> bool {
+        match c as u32 {
+            0x1100..=0x115F | 0xA960..=0xA97C => true,
+            _ => false,
+        }
+    }
+
+    fn is_hangul_v(c: char) -> bool {
+        match c as u32 {
+            0x1160..=0x11A7 | 0xD7B0..=0xD7C6 => true,
+            _ => false,
+        }
+    }
+
+    fn is_hangul_t(c: char) -> bool {
+        match c as u32 {
+            0x11A8..=0x11FF | 0xD7CB..=0xD7FB => true,
+            _ => false,
+        }
+    }
+
+    /// Precomposed Hangul syllables (U+AC00..=U+D7A3) decompose
+    /// algorithmically into LV (no trailing consonant) or LVT.
+    fn hangul_syllable_class(c: char) -> Option<Class> {
+        let cp = c as u32;
+        if cp < 0xAC00 || cp > 0xD7A3 {
+            return None;
+        }
+        let s_index = cp - 0xAC00;
+        Some(if s_index % 28 == 0 { Class::LV } else { Class::LVT })
+    }
+
+    fn is_control(c: char) -> bool {
+        // GB4/GB5 also apply to CR/LF, but those are classified earlier.
+        // This covers the C0/C1 control characters plus a few Format and
+        // separator codepoints (U+200B, U+200E, U+200F, U+2028, U+2029,
+        // U+FEFF); other Format and unassigned codepoints are not covered.
+        match c as u32 {
+            0x0000..=0x0009 | 0x000B..=0x000C | 0x000E..=0x001F => true,
+            0x007F..=0x009F => true,
+            0x200B | 0x200E | 0x200F => true,
+            0x2028 | 0x2029 => true,
+            0xFEFF => true,
+            _ => c.is_control(),
+        }
+    }
+
+    fn is_prepend(c: char) -> bool {
+        match c as u32 {
+            0x0600..=0x0605 | 0x06DD | 0x070F | 0x0890..=0x0891
+            | 0x08E2 | 0x0D4E | 0x110BD | 0x110CD
+            | 0x111C2..=0x111C3 | 0x1193F | 0x11941
+            | 0x11A3A | 0x11A84..=0x11A89 | 0x11D46 => true,
+            _ => false,
+        }
+    }
+
+    fn is_extend(c: char) -> bool {
+        match c as u32 {
+            0x0300..=0x036F => true, // Combining Diacritical Marks
+            0x0483..=0x0489 => true,
+            0x0591..=0x05BD | 0x05BF | 0x05C1..=0x05C2
+                | 0x05C4..=0x05C5 | 0x05C7 => true,
+            0x0610..=0x061A => true,
+            0x064B..=0x065F | 0x0670 => true,
+            0x06D6..=0x06DC | 0x06DF..=0x06E4 | 0x06E7..=0x06E8
+                | 0x06EA..=0x06ED => true,
+            0x0711 | 0x0730..=0x074A => true,
+            0x07A6..=0x07B0 => true,
+            0x0816..=0x0819 | 0x081B..=0x0823 | 0x0825..=0x0827
+                | 0x0829..=0x082D => true,
+            0x0900..=0x0902 | 0x093A | 0x093C | 0x0941..=0x0948
+                | 0x094D | 0x0951..=0x0957 | 0x0962..=0x0963 => true,
+            0x0981 | 0x09BC | 0x09C1..=0x09C4 | 0x09CD
+                | 0x09E2..=0x09E3 => true,
+            0x0A01..=0x0A02 | 0x0A3C | 0x0A41..=0x0A42
+                | 0x0A47..=0x0A48 | 0x0A4B..=0x0A4D | 0x0A51
+                | 0x0A70..=0x0A71 | 0x0A75 => true,
+            0x0A81..=0x0A82 | 0x0ABC | 0x0AC1..=0x0AC5
+                | 0x0AC7..=0x0AC8 | 0x0ACD | 0x0AE2..=0x0AE3 => true,
+            0x0B01 | 0x0B3C | 0x0B3F | 0x0B41..=0x0B44 | 0x0B4D
+                | 0x0B56 | 0x0B62..=0x0B63 => true,
+            0x0B82 | 0x0BC0 | 0x0BCD => true,
+            0x0C00 | 0x0C3E..=0x0C40 | 0x0C46..=0x0C48
+                | 0x0C4A..=0x0C4D | 0x0C55..=0x0C56
+                | 0x0C62..=0x0C63 => true,
+            0x0C81 | 0x0CBC | 0x0CBF | 0x0CC6 | 0x0CCC..=0x0CCD
+                | 0x0CE2..=0x0CE3 => true,
+            0x0D01 | 0x0D41..=0x0D44 | 0x0D4D | 0x0D62..=0x0D63 => true,
+            0x0DCA | 0x0DD2..=0x0DD4 | 0x0DD6 => true,
+            0x0E31 | 0x0E34..=0x0E3A | 0x0E47..=0x0E4E => true,
+            0x0EB1 | 0x0EB4..=0x0EBC | 0x0EC8..=0x0ECD => true,
+            0x0F18..=0x0F19 | 0x0F35 | 0x0F37 | 0x0F39
+                | 0x0F71..=0x0F7E | 0x0F80..=0x0F84
+                | 0x0F86..=0x0F87 | 0x0F8D..=0x0F97
+                | 0x0F99..=0x0FBC | 0x0FC6 => true,
+            0x102D..=0x1030 | 0x1032..=0x1037 | 0x1039..=0x103A
+                | 0x103D..=0x103E | 0x1058..=0x1059
+                | 0x105E..=0x1060 | 0x1071..=0x1074 | 0x1082
+                | 0x1085..=0x1086 | 0x108D => true,
+            0x135D..=0x135F => true,
+            0x1AB0..=0x1ACE => true,
+            0x1DC0..=0x1DFF => true,
+            0x200C => true, // Zero Width Non-Joiner
+            0x20D0..=0x20FF => true, // Combining Diacritical Marks for Symbols
+            0xFE00..=0xFE0F => true, // Variation Selectors
+            0xFE20..=0xFE2F => true, // Combining Half Marks
+            0x1F3FB..=0x1F3FF => true, // Emoji skin tone modifiers
+            0xE0020..=0xE007F => true, // Tag characters
+            0xE0100..=0xE01EF => true, // Variation Selectors Supplement
+            _ => false,
+        }
+    }
+
+    fn is_spacing_mark(c: char) -> bool {
+        match c as u32 {
+            0x0903 | 0x093B | 0x093E..=0x0940 | 0x0949..=0x094C
+                | 0x094E..=0x094F => true,
+            0x0982..=0x0983 | 0x09BF..=0x09C0 | 0x09C7..=0x09C8
+                | 0x09CB..=0x09CC => true,
+            0x0A03 | 0x0A3E..=0x0A40 => true,
+            0x0A83 | 0x0AC0 | 0x0AC9 | 0x0ACB..=0x0ACC => true,
+            0x0B02..=0x0B03 | 0x0B3E | 0x0B47..=0x0B48
+                | 0x0B4B..=0x0B4C => true,
+            0x0BBF | 0x0BC1..=0x0BC2 | 0x0BC6..=0x0BC8
+                | 0x0BCA..=0x0BCC => true,
+            0x0C01..=0x0C03 | 0x0C41..=0x0C44 => true,
+            0x0C82..=0x0C83 | 0x0CBE | 0x0CC0..=0x0CC4
+                | 0x0CC7..=0x0CC8 | 0x0CCA..=0x0CCB => true,
+            0x0D02..=0x0D03 | 0x0D3F..=0x0D40 | 0x0D46..=0x0D48
+                | 0x0D4A..=0x0D4C => true,
+            0x0D82..=0x0D83 | 0x0DD0..=0x0DD1 | 0x0DD8..=0x0DDE => true,
+            0x0E33 | 0x0EB3 => true,
+            0x1031 | 0x103B..=0x103C | 0x1056..=0x1057 => true,
+            _ => false,
+        }
+    }
+
+    fn is_extended_pictographic(c: char) -> bool {
+        match c as u32 {
+            0x00A9 | 0x00AE | 0x203C | 0x2049 | 0x2122 | 0x2139 => true,
+            0x2194..=0x21AA => true,
+            0x231A..=0x231B => true,
+            0x2600..=0x27BF => true, // Misc Symbols, Dingbats
+            0x2B00..=0x2BFF => true, // Misc Symbols and Arrows
+            0x1F000..=0x1FFFF => true, // Emoji, Supplemental Symbols, etc.
+            _ => false,
+        }
+    }
+
+    /// Scans backward from `zwj_start` (the byte offset of the ZWJ itself)
+    /// over a run of `Extend` codepoints, and reports whether that run is
+    /// immediately preceded by an `Extended_Pictographic` codepoint, per
+    /// the `\p{Extended_Pictographic} Extend* ZWJ` half of GB11.
+    pub fn zwj_preceded_by_pictographic(bytes: &[u8], zwj_start: usize) -> bool {
+        let mut pos = zwj_start;
+        loop {
+            match decode_last_utf8(&bytes[..pos]) {
+                None => return false,
+                Some((c, len)) => match classify(c) {
+                    Class::Extend => pos -= len,
+                    Class::ExtendedPictographic => return true,
+                    _ => return false,
+                },
+            }
+        }
+    }
+
+    /// Counts the run of consecutive `Regional_Indicator` codepoints ending
+    /// with the RI at `before_start` (the byte offset of the RI that sits
+    /// just before the boundary under test), that RI included, and reports
+    /// whether that run has odd length, per GB12/GB13's pairing rule.
+    pub fn odd_regional_indicator_run(bytes: &[u8], before_start: usize) -> bool {
+        let mut count = 1; // the RI at `before_start` itself
+        let mut pos = before_start;
+        loop {
+            match decode_last_utf8(&bytes[..pos]) {
+                Some((c, len)) if classify(c) == Class::RegionalIndicator => {
+                    count += 1;
+                    pos -= len;
+                }
+                _ => break,
+            }
+        }
+        count % 2 == 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A minimal external `Input` impl over a byte slice that's already
+    /// known to be Latin-1, decoding each byte directly to its codepoint
+    /// instead of running the UTF-8 decoder `CharInput` uses. It builds
+    /// every `InputAt` via `InputAt::new` — the whole point of that
+    /// constructor being `pub` — and implements `prefix_at` directly, the
+    /// same way `CharInput`/`ByteInput` do.
+    ///
+    /// This exists to prove the trait is actually implementable outside
+    /// this module without reaching into anything private — see the NOTE
+    /// on `Input` for what's still missing before a downstream crate could
+    /// write this same impl against a published re-export.
+    struct Latin1Input<'t>(&'t [u8]);
+
+    impl<'t> Input for Latin1Input<'t> {
+        fn at(&self, i: usize) -> InputAt {
+            match self.0.get(i) {
+                None => InputAt::new(i, 0, None.into(), None),
+                Some(&b) => InputAt::new(i, 1, (b as char).into(), None),
+            }
+        }
+
+        fn next_char(&self, at: InputAt) -> Char {
+            at.char()
+        }
+
+        fn previous_char(&self, at: InputAt) -> Char {
+            if at.pos() == 0 {
+                None.into()
+            } else {
+                (self.0[at.pos() - 1] as char).into()
+            }
+        }
+
+        fn prefix_at(
+            &self,
+            prefixes: &LiteralSearcher,
+            at: InputAt,
+        ) -> Option<InputAt> {
+            prefixes.find(&self.0[at.pos()..]).map(|(s, _)| self.at(at.pos() + s))
+        }
+
+        fn len(&self) -> usize {
+            self.0.len()
+        }
+
+        fn as_bytes(&self) -> &[u8] {
+            self.0
+        }
+    }
+
+    #[test]
+    fn external_input_impl_walks_like_the_builtins() {
+        let text = b"ab";
+        let input = Latin1Input(text);
+
+        let start = input.at(0);
+        assert!(start.is_start());
+        assert_eq!(start.char().as_char(), Some('a'));
+
+        let next = input.at(start.next_pos());
+        assert_eq!(next.char().as_char(), Some('b'));
+        assert_eq!(input.previous_char(next).as_char(), Some('a'));
+
+        let end = input.at(input.len());
+        assert!(end.is_end());
+        assert_eq!(input.next_char(end).as_char(), None);
+    }
+
+    #[test]
+    fn byteset_find_and_rfind_across_set_sizes() {
+        let haystack = b"xxxaxxxbxxxcxxxdxxx";
+
+        let one = ByteSet::from_bytes(b"a");
+        assert_eq!(one.find(haystack), Some(3));
+        assert_eq!(one.rfind(haystack), Some(3));
+
+        let two = ByteSet::from_bytes(b"ab");
+        assert_eq!(two.find(haystack), Some(3));
+        assert_eq!(two.rfind(haystack), Some(7));
+
+        let three = ByteSet::from_bytes(b"abc");
+        assert_eq!(three.find(haystack), Some(3));
+        assert_eq!(three.rfind(haystack), Some(11));
+
+        let four = ByteSet::from_bytes(b"abcd");
+        assert_eq!(four.find(haystack), Some(3));
+        assert_eq!(four.rfind(haystack), Some(15));
+
+        let empty = ByteSet::default();
+        assert_eq!(empty.find(haystack), None);
+        assert_eq!(empty.rfind(haystack), None);
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn char_input_ascii_fast_path_matches_general_decoding() {
+        let input = CharInput::new(b"abc");
+        let at0 = input.at(0);
+        assert_eq!(at0.char().as_char(), Some('a'));
+        assert_eq!(at0.len(), 1);
+
+        let at3 = input.at(3);
+        assert!(at3.is_end());
+        assert_eq!(input.previous_char(at3).as_char(), Some('c'));
+
+        // A non-ASCII input doesn't get the whole-input ASCII flag: its ASCII
+        // bytes still take the per-byte fast path, and the rest go through
+        // the multi-byte decoder.
+        let mixed = CharInput::new("a\u{00e9}".as_bytes());
+        let mixed_at0 = mixed.at(0);
+        assert_eq!(mixed_at0.char().as_char(), Some('a'));
+        assert_eq!(mixed_at0.len(), 1);
+        let mixed_at1 = mixed.at(1);
+        assert_eq!(mixed_at1.char().as_char(), Some('\u{00e9}'));
+        assert_eq!(mixed_at1.len(), 2);
+    }
+
+    #[test]
+    fn grapheme_boundary_gb11_keeps_pictographic_zwj_sequence_joined() {
+        // U+1F600 (grinning face, Extended_Pictographic) ZWJ U+1F600: GB11
+        // says don't break before the second emoji.
+        let text = "\u{1F600}\u{200D}\u{1F600}";
+        let input = CharInput::new(text.as_bytes());
+        let zwj_end = input.at("\u{1F600}\u{200D}".len());
+        assert!(!input.is_grapheme_boundary(zwj_end));
+    }
+
+    #[test]
+    fn grapheme_boundary_gb12_13_pairs_regional_indicators() {
+        // "RRRR" as Regional_Indicator codepoints: GB12/GB13 pair them up,
+        // so there's a boundary between the first pair and the second but
+        // not inside either pair.
+        let text = "\u{1F1E6}\u{1F1E7}\u{1F1E8}\u{1F1E9}"; // RI RI RI RI
+        let input = CharInput::new(text.as_bytes());
+
+        let after_first = input.at("\u{1F1E6}".len());
+        assert!(!input.is_grapheme_boundary(after_first));
+
+        let after_pair = input.at("\u{1F1E6}\u{1F1E7}".len());
+        assert!(input.is_grapheme_boundary(after_pair));
+
+        let after_third = input.at("\u{1F1E6}\u{1F1E7}\u{1F1E8}".len());
+        assert!(!input.is_grapheme_boundary(after_third));
+    }
+}